@@ -0,0 +1,157 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::time::Duration;
+
+#[derive(Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ConfigServer,
+
+    #[serde(default)]
+    pub dns: ConfigDNS,
+
+    #[serde(default)]
+    pub store: ConfigStore,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ConfigServer {
+    pub log_level: String,
+}
+
+impl Default for ConfigServer {
+    fn default() -> ConfigServer {
+        ConfigServer {
+            log_level: "warn".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ConfigDNS {
+    pub inet: String,
+    pub port: u16,
+    pub flatten: ConfigDNSFlatten,
+    pub forwarders: Option<ConfigDNSForwarders>,
+}
+
+impl Default for ConfigDNS {
+    fn default() -> ConfigDNS {
+        ConfigDNS {
+            inet: "0.0.0.0".to_string(),
+            port: 53,
+            flatten: ConfigDNSFlatten::default(),
+            forwarders: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct ConfigDNSFlatten {
+    pub resolver: Option<ConfigDNSResolver>,
+
+    #[serde(default)]
+    pub negative: ConfigDNSFlattenNegative,
+}
+
+impl ConfigDNSFlatten {
+    pub fn negative_floor(&self) -> Duration {
+        Duration::from_secs(self.negative.floor)
+    }
+
+    pub fn negative_ceiling(&self) -> Duration {
+        Duration::from_secs(self.negative.ceiling)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ConfigDNSResolver {
+    pub nameservers: Vec<String>,
+    pub protocol: String,
+    pub timeout: u64,
+    pub attempts: usize,
+    pub resolv_file: Option<String>,
+
+    // Notice: required when 'protocol' is 'tls', so the upstream's certificate can be validated \
+    //   against an expected name.
+    pub tls_dns_name: Option<String>,
+}
+
+impl Default for ConfigDNSResolver {
+    fn default() -> ConfigDNSResolver {
+        ConfigDNSResolver {
+            nameservers: Vec::new(),
+            protocol: "udp".to_string(),
+            timeout: 5,
+            attempts: 2,
+            resolv_file: None,
+            tls_dns_name: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ConfigDNSFlattenNegative {
+    pub floor: u64,
+    pub ceiling: u64,
+}
+
+impl Default for ConfigDNSFlattenNegative {
+    fn default() -> ConfigDNSFlattenNegative {
+        ConfigDNSFlattenNegative {
+            floor: 5,
+            ceiling: 300,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ConfigDNSForwarders {
+    pub enabled: bool,
+    pub nameservers: Vec<String>,
+    pub protocol: String,
+    pub timeout: u64,
+    pub attempts: usize,
+    pub allowlist: Option<Vec<String>>,
+
+    // Notice: required when 'protocol' is 'tls', so the upstream's certificate can be validated \
+    //   against an expected name.
+    pub tls_dns_name: Option<String>,
+}
+
+impl Default for ConfigDNSForwarders {
+    fn default() -> ConfigDNSForwarders {
+        ConfigDNSForwarders {
+            enabled: false,
+            nameservers: Vec::new(),
+            protocol: "udp".to_string(),
+            timeout: 5,
+            attempts: 2,
+            allowlist: None,
+            tls_dns_name: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ConfigStore {
+    pub redis_url: String,
+}
+
+impl Default for ConfigStore {
+    fn default() -> ConfigStore {
+        ConfigStore {
+            redis_url: "redis://127.0.0.1:6379/0".to_string(),
+        }
+    }
+}
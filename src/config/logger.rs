@@ -0,0 +1,41 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+pub struct ConfigLogger;
+
+struct ConfigLoggerOutput;
+
+static LOGGER: ConfigLoggerOutput = ConfigLoggerOutput;
+
+impl ConfigLogger {
+    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_logger(&LOGGER).map(|()| log::set_max_level(level))
+    }
+}
+
+impl Log for ConfigLoggerOutput {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level_label = match record.level() {
+                Level::Error => "erro",
+                Level::Warn => "warn",
+                Level::Info => "info",
+                Level::Debug => "debg",
+                Level::Trace => "trce",
+            };
+
+            println!("{} -- {}", level_label, record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,34 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::fs;
+
+use toml;
+
+use super::config::Config;
+use APP_ARGS;
+
+pub struct ConfigReader;
+
+impl ConfigReader {
+    pub fn make() -> Config {
+        Self::from_path(&APP_ARGS.config)
+    }
+
+    fn from_path(path: &str) -> Config {
+        match fs::read_to_string(path) {
+            Ok(contents) => match toml::from_str(&contents) {
+                Ok(config) => config,
+                Err(err) => {
+                    panic!("invalid config file '{}': {}", path, err);
+                }
+            },
+            Err(err) => {
+                panic!("could not read config file '{}': {}", path, err);
+            }
+        }
+    }
+}
@@ -4,26 +4,28 @@
 // Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
 // License: Mozilla Public License v2.0 (MPL v2.0)
 
+use std::cmp;
 use std::ops::Deref;
-use std::collections::HashMap;
 use std::thread;
-use std::sync::RwLock;
-use std::time::{SystemTime, Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use trust_dns_resolver::Resolver;
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::proto::rr::{RData, RecordType as NativeRecordType};
 
-use super::record::{RecordValues, RecordValue, RecordType};
+use super::metrics::DNS_FLATTEN_METRICS;
+use super::record::{concat_txt_segments, format_caa_value, RecordType, RecordValue, RecordValues};
+use super::registry::{RecordRegistry, RegistryKey};
+use super::resolver::{ResolverBuilder, ResolverSettings};
+use APP_CONF;
 
 lazy_static! {
-    pub static ref DNS_BOOTSTRAP: RwLock<HashMap<DNSFlattenRegistryKey, u32>> = RwLock::new(HashMap::new());
     pub static ref DNS_FLATTEN: DNSFlatten = DNSFlattenBuilder::new();
 }
 
 struct DNSFlattenBuilder;
 
 pub struct DNSFlatten {
-    registry: RwLock<HashMap<DNSFlattenRegistryKey, DNSFlattenEntry>>,
+    registry: RecordRegistry,
     resolver: Resolver,
 }
 
@@ -33,34 +35,47 @@ pub struct DNSFlattenBootstrap;
 pub struct DNSFlattenMaintainBuilder;
 pub struct DNSFlattenMaintain;
 
-type DNSFlattenRegistryKey = (RecordValue, RecordType);
-
 const MAINTAIN_EXPIRE_TTL_RATIO: u32 = 10;
 const MAINTAIN_PERFORM_INTERVAL: Duration = Duration::from_secs(60);
 const BOOTSTRAP_TICK_INTERVAL: Duration = Duration::from_millis(100);
 
-struct DNSFlattenEntry {
-    values: RecordValues,
-    ttl: u32,
-    refreshed_at: SystemTime,
-    accessed_at: SystemTime,
+// Notice: a resolved TTL of zero would make an entry look perpetually stale to the maintain \
+//   tick's expire/refresh scans (`elapsed >= ttl` is true as soon as any time passes at all), \
+//   so a resolved-but-already-expiring record is floored to 1 second instead.
+const MIN_RESOLVED_TTL: u32 = 1;
+
+// Converts a resolver's 'valid_until' deadline (the actual upstream TTL, as cached by \
+//   trust-dns) into the number of seconds remaining, floored to 'MIN_RESOLVED_TTL'.
+fn ttl_from_valid_until(valid_until: Instant) -> u32 {
+    let now = Instant::now();
+
+    if valid_until > now {
+        cmp::max((valid_until - now).as_secs() as u32, MIN_RESOLVED_TTL)
+    } else {
+        MIN_RESOLVED_TTL
+    }
 }
 
 impl DNSFlattenBuilder {
     fn new() -> DNSFlatten {
-        // Acquire a resolver (prefer using system resolver)
-        let resolver = if let Ok(resolver) = Resolver::from_system_conf() {
-            info!("dns flatten resolver acquired from system");
-
-            resolver
+        let resolver = if let Some(ref resolver_config) = APP_CONF.dns.flatten.resolver {
+            debug!("dns flatten resolver acquired from explicit configuration");
+
+            ResolverBuilder::from_settings(&ResolverSettings {
+                nameservers: resolver_config.nameservers.clone(),
+                protocol: resolver_config.protocol.clone(),
+                timeout: resolver_config.timeout,
+                attempts: resolver_config.attempts,
+                resolv_file: resolver_config.resolv_file.clone(),
+                tls_dns_name: resolver_config.tls_dns_name.clone(),
+            })
         } else {
-            warn!("dns flatten resolver could not be acquired from system, using default resolver");
-
-            Resolver::new(ResolverConfig::default(), ResolverOpts::default()).unwrap()
+            // No explicit resolver configured, fall back to system resolver (or default)
+            ResolverBuilder::from_system_or_default()
         };
 
         DNSFlatten {
-            registry: RwLock::new(HashMap::new()),
+            registry: RecordRegistry::new(),
             resolver: resolver,
         }
     }
@@ -82,110 +97,184 @@ impl DNSFlattenMaintainBuilder {
 }
 
 impl DNSFlatten {
-    pub fn pass(&self, record_type: RecordType, record_value: RecordValue, record_ttl: u32) -> Result<RecordValues, ()> {
+    pub fn pass(&self, record_type: RecordType, record_value: RecordValue, record_ttl: u32) -> Result<(RecordValues, u32), ()> {
         debug!("flatten registry pass on value: {:?} and type: {:?}", record_value, record_type);
 
         // Acquire registry key
         let registry_key = (record_value, record_type);
 
-        // Acquire flattened value from cache (if any)
-        // Notice: this is nested in a sub-block as to ensure no rw-lock dead-lock can occur due \
-        //   later use of the same lock from this block level.
-        let cached_value = {
-            // Acquire registry write pointer
-            let mut registry_write = self.registry.write().unwrap();
-
-            if let Some(ref mut registry_record) = registry_write.get_mut(&registry_key) {
-                debug!("flattening from local registry on value: {:?} and type: {:?}", registry_key.0, registry_key.1);
-
-                // Bump last access time
-                registry_record.accessed_at = SystemTime::now();
-
-                Some(registry_record.values.to_owned())
-            } else {
-                None
-            }
-        };
-
         // Return cached value, or queue flatten order?
-        if let Some(value) = cached_value {
+        if let Some(value) = self.registry.lookup(&registry_key) {
+            debug!("flattening from local registry on value: {:?} and type: {:?}", registry_key.0, registry_key.1);
+
             Ok(value)
+        } else if self.registry.is_in_backoff(&registry_key) {
+            debug!("flattening short-circuited on value: {:?} and type: {:?} (in backoff)", registry_key.0, registry_key.1);
+
+            Err(())
         } else {
             info!("flattening from network on value: {:?} and type: {:?}", registry_key.0, registry_key.1);
 
-            self.queue(&registry_key, record_ttl)
-        }
-    }
-
-    fn queue(&self, registry_key: &DNSFlattenRegistryKey, ttl: u32) -> Result<RecordValues, ()> {
-        // Acquire registry write pointer
-        let mut bootstrap_write = DNS_BOOTSTRAP.write().unwrap();
+            self.registry.queue(&registry_key, record_ttl);
 
-        // Stack flatten order to queue (will be picked up by worker thread ASAP)
-        bootstrap_write.insert(registry_key.to_owned(), ttl);
-
-        // Send back an error, as we do not have the flat value at this point in time
-        // Notice: this will propagate a 'SERVFAIL', which ensures resolvers do not cache the \
-        //   empty response.
-        Err(())
+            // Send back an error, as we do not have the flat value at this point in time
+            // Notice: this will propagate a 'SERVFAIL', which ensures resolvers do not cache the \
+            //   empty response.
+            Err(())
+        }
     }
 
-    fn flatten(&self, registry_key: &DNSFlattenRegistryKey, ttl: u32, accessed_at: Option<SystemTime>) {
-        // Convert each value type into its string representation
-        let values: Result<Vec<String>, ResolveError> = match registry_key.1 {
+    fn flatten(&self, registry_key: &RegistryKey, ttl: u32, accessed_at: Option<SystemTime>) {
+        // Convert each value type into its string representation, alongside the TTL actually \
+        //   advertised by the upstream answer (falling back to the seed 'ttl' for the \
+        //   pass-through record types that perform no lookup of their own).
+        let resolved: Result<(Vec<String>, u32), ResolveError> = match registry_key.1 {
             RecordType::A => {
                 self.resolver.ipv4_lookup(&registry_key.0).map(|values| {
-                    values.iter().map(|value| value.to_string()).collect()
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| value.to_string()).collect();
+
+                    (data, resolved_ttl)
                 })
             },
             RecordType::AAAA => {
                 self.resolver.ipv6_lookup(&registry_key.0).map(|values| {
-                    values.iter().map(|value| value.to_string()).collect()
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| value.to_string()).collect();
+
+                    (data, resolved_ttl)
                 })
             },
             RecordType::MX => {
                 // Format as `{priority} {exchange}`, eg. `10 inbound.crisp.email`
                 self.resolver.mx_lookup(&registry_key.0).map(|values| {
-                    values.iter().map(|value| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
                         format!("{} {}", value.preference(), value.exchange())
-                    }).collect()
+                    }).collect();
+
+                    (data, resolved_ttl)
                 })
             },
             RecordType::TXT => {
-                // Assemble all TXT data segments
+                // Concatenate the raw segment bytes of each TXT record first, then decode the \
+                //   whole payload once: decoding each 255-byte segment on its own (as done \
+                //   previously) mangles any multi-byte UTF-8 character straddling a segment \
+                //   boundary, breaking DKIM/SPF concatenation semantics.
                 self.resolver.txt_lookup(&registry_key.0).map(|values| {
-                    values.iter().map(|value| value.txt_data().join("")).collect()
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
+                        concat_txt_segments(value.txt_data().iter().map(|segment| segment.as_ref()))
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::SRV => {
+                // Format as `{priority} {weight} {port} {target}`, eg. `10 5 5060 sip.crisp.chat`
+                self.resolver.srv_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
+                        format!("{} {} {} {}", value.priority(), value.weight(), value.port(), value.target())
+                    }).collect();
+
+                    (data, resolved_ttl)
                 })
             },
-            RecordType::PTR | RecordType::CNAME => Ok(Vec::new()),
+            RecordType::NS => {
+                self.resolver.lookup(&*registry_key.0, NativeRecordType::NS).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().filter_map(|value| {
+                        match value {
+                            RData::NS(name) => Some(name.to_string()),
+                            _ => None,
+                        }
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::CAA => {
+                // Format as `{flags} {tag} "{value}"`, eg. `0 issue "letsencrypt.org"`
+                self.resolver.lookup(&*registry_key.0, NativeRecordType::CAA).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().filter_map(|value| {
+                        match value {
+                            RData::CAA(caa) => {
+                                let flags = if caa.issuer_critical() { 128 } else { 0 };
+
+                                Some(format!("{} {} \"{}\"", flags, caa.tag(), format_caa_value(caa.value())))
+                            },
+                            _ => None,
+                        }
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::PTR | RecordType::CNAME => Ok((Vec::new(), ttl)),
         };
 
         // Return final flattened record values
-        let results = if let Ok(values) = values {
-            Ok(RecordValues::from_list(values.into_iter().map(|value| {
-                RecordValue::from_string(value)
+        let results = if let Ok((ref values, _)) = resolved {
+            Ok(RecordValues::from_list(values.iter().map(|value| {
+                RecordValue::from_string(value.to_owned())
             }).collect()))
         } else {
             Err(())
         };
 
-        // Acquire registry write pointer
-        let mut registry_write = self.registry.write().unwrap();
+        let resolved_ttl = resolved.as_ref().map(|(_, resolved_ttl)| *resolved_ttl).unwrap_or(ttl);
 
-        // Error was acquired, and a flattened records already exist in registry?
-        // Notice: this prevents in-error refreshes to empty the registry where it previously \
-        //   had records, effectively corrupting the DNS system.
-        if results.is_err() && registry_write.contains_key(registry_key) {
-            warn!("dns flattening in error on value: {:?} and type: {:?}, keeping old cache", registry_key.0, registry_key.1);
-        } else {
-            // Store flattened values to registry
-            registry_write.insert(
-                registry_key.to_owned(),
+        if results.is_err() {
+            // Bump (or create) the negative cache entry, so that repeated failures back off \
+            //   exponentially instead of hammering the upstream resolver on every query.
+            let backoff = self.registry.negative_bump(
+                registry_key,
+                APP_CONF.dns.flatten.negative_floor(),
+                APP_CONF.dns.flatten.negative_ceiling(),
+            );
 
-                DNSFlattenEntry::new(
-                    results.unwrap_or(RecordValues::new()), ttl, accessed_at
-                )
+            debug!(
+                "dns flattening negative-cached on value: {:?} and type: {:?} (backoff: {}s)",
+                registry_key.0, registry_key.1, backoff.as_secs()
             );
+
+            DNS_FLATTEN_METRICS.track_failure();
+            DNS_FLATTEN_METRICS.track_negative_cached();
+
+            // Error was acquired, and a flattened records already exist in registry?
+            // Notice: this prevents in-error refreshes to empty the registry where it previously \
+            //   had records, effectively corrupting the DNS system.
+            if self.registry.contains(registry_key) {
+                warn!("dns flattening in error on value: {:?} and type: {:?}, keeping old cache", registry_key.0, registry_key.1);
+            }
+        } else {
+            // Lookup succeeded, clear any previously-tracked negative cache entry
+            self.registry.negative_clear(registry_key);
+
+            let new_values = results.unwrap_or(RecordValues::new());
+
+            // Store flattened values to registry, and compare against the previously-cached \
+            //   values (if any), so that a silent re-point of an upstream ALIAS/CNAME target is \
+            //   surfaced rather than swallowed.
+            match self.registry.store(registry_key, new_values.clone(), resolved_ttl, accessed_at) {
+                Some(previous_values) => {
+                    DNS_FLATTEN_METRICS.track_refresh();
+
+                    if previous_values != new_values {
+                        warn!(
+                            "dns flatten change detected on value: {:?} and type: {:?}, old: {:?}, new: {:?}",
+                            registry_key.0, registry_key.1, previous_values, new_values
+                        );
+
+                        DNS_FLATTEN_METRICS.track_change();
+                    }
+                }
+                None => {
+                    DNS_FLATTEN_METRICS.track_entry();
+                }
+            }
         }
     }
 }
@@ -203,22 +292,21 @@ impl DNSFlattenBootstrap {
     }
 
     fn tick() {
-        let mut bootstrap_register: Vec<(DNSFlattenRegistryKey, u32)> = Vec::new();
-
-        // Scan for items to be bootstrapped
-        {
-            let bootstrap_read = DNS_BOOTSTRAP.read().unwrap();
-
-            for (bootstrap_key, bootstrap_ttl) in bootstrap_read.iter() {
-                bootstrap_register.push((bootstrap_key.to_owned(), *bootstrap_ttl));
-            }
-        }
+        let bootstrap_register = DNS_FLATTEN.registry.drain_bootstrap();
 
         // Proceed bootstrapping items
         if bootstrap_register.is_empty() == false {
             for (bootstrap_key, bootstrap_ttl) in bootstrap_register.iter() {
+                // Skip keys that are still inside their negative-cache backoff window, so a \
+                //   consistently-failing lookup does not get re-bootstrapped on every tick.
+                if DNS_FLATTEN.registry.is_in_backoff(bootstrap_key) {
+                    debug!("skipping bootstrap of value: {:?} and type: {:?} (in backoff)", bootstrap_key.0, bootstrap_key.1);
+
+                    continue;
+                }
+
                 DNS_FLATTEN.flatten(bootstrap_key, *bootstrap_ttl, None);
-                DNS_BOOTSTRAP.write().unwrap().remove(bootstrap_key);
+                DNS_FLATTEN.registry.remove_bootstrap(bootstrap_key);
             }
 
             debug!(
@@ -260,61 +348,15 @@ impl DNSFlattenMaintain {
     fn expire() {
         debug!("flushing expired dns flattened records");
 
-        let mut expire_register: Vec<DNSFlattenRegistryKey> = Vec::new();
-
-        // Scan for expired registry items
-        {
-            let registry_read = DNS_FLATTEN.registry.read().unwrap();
-            let now_time = SystemTime::now();
+        let expired_count = DNS_FLATTEN.registry.expire(MAINTAIN_EXPIRE_TTL_RATIO);
 
-            for (registry_key, registry_entry) in registry_read.iter() {
-                let registry_elapsed = now_time
-                    .duration_since(registry_entry.accessed_at)
-                    .unwrap()
-                    .as_secs();
-
-                if registry_elapsed >= (registry_entry.ttl * MAINTAIN_EXPIRE_TTL_RATIO) as u64 {
-                    expire_register.push(registry_key.to_owned());
-                }
-            }
-        }
-
-        // Any registry item to expire?
-        if expire_register.is_empty() == false {
-            let mut registry_write = DNS_FLATTEN.registry.write().unwrap();
-
-            for registry_key in &expire_register {
-                registry_write.remove(registry_key);
-            }
-        }
-
-        info!(
-            "flushed expired dns flattened records (count: {})",
-            expire_register.len()
-        );
+        info!("flushed expired dns flattened records (count: {})", expired_count);
     }
 
     fn refresh() {
         debug!("refreshing dns flattened records");
 
-        let mut refresh_register: Vec<(DNSFlattenRegistryKey, u32, SystemTime)> = Vec::new();
-
-        // Scan for to-be-refreshed registry items
-        {
-            let registry_read = DNS_FLATTEN.registry.read().unwrap();
-            let now_time = SystemTime::now();
-
-            for (registry_key, registry_entry) in registry_read.iter() {
-                let registry_elapsed = now_time
-                    .duration_since(registry_entry.refreshed_at)
-                    .unwrap()
-                    .as_secs();
-
-                if registry_elapsed >= registry_entry.ttl as u64 {
-                    refresh_register.push((registry_key.to_owned(), registry_entry.ttl, registry_entry.accessed_at));
-                }
-            }
-        }
+        let refresh_register = DNS_FLATTEN.registry.refresh_candidates();
 
         // Any registry item to refresh?
         if refresh_register.is_empty() == false {
@@ -331,16 +373,3 @@ impl DNSFlattenMaintain {
         );
     }
 }
-
-impl DNSFlattenEntry {
-    fn new(values: RecordValues, ttl: u32, accessed_at: Option<SystemTime>) -> DNSFlattenEntry {
-        let time_now = SystemTime::now();
-
-        DNSFlattenEntry {
-            values: values,
-            ttl: ttl,
-            refreshed_at: time_now,
-            accessed_at: accessed_at.unwrap_or(time_now),
-        }
-    }
-}
@@ -0,0 +1,338 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::cmp;
+use std::ops::Deref;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use trust_dns_resolver::Resolver;
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::proto::rr::{RData, RecordType as NativeRecordType};
+
+use super::record::{concat_txt_segments, format_caa_value, RecordType, RecordValue, RecordValues};
+use super::registry::{RecordRegistry, RegistryKey};
+use super::resolver::{ResolverBuilder, ResolverSettings};
+use APP_CONF;
+
+lazy_static! {
+    pub static ref DNS_FORWARD: DNSForward = DNSForwardBuilder::new();
+}
+
+struct DNSForwardBuilder;
+
+// Notice: the forwarder shares its caching engine (registry, bootstrap queue, expire/refresh \
+//   scan) with the flatten subsystem via 'RecordRegistry', rather than re-implementing it.
+pub struct DNSForward {
+    registry: RecordRegistry,
+    resolver: Option<Resolver>,
+}
+
+pub struct DNSForwardBootstrapBuilder;
+pub struct DNSForwardBootstrap;
+
+pub struct DNSForwardMaintainBuilder;
+pub struct DNSForwardMaintain;
+
+const MAINTAIN_EXPIRE_TTL_RATIO: u32 = 10;
+const MAINTAIN_PERFORM_INTERVAL: Duration = Duration::from_secs(60);
+const BOOTSTRAP_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Notice: a resolved TTL of zero would make an entry look perpetually stale to the maintain \
+//   tick's expire/refresh scans (`elapsed >= ttl` is true as soon as any time passes at all), \
+//   so a resolved-but-already-expiring record is floored to 1 second instead.
+const MIN_RESOLVED_TTL: u32 = 1;
+
+// Converts a resolver's 'valid_until' deadline (the actual upstream TTL, as cached by \
+//   trust-dns) into the number of seconds remaining, floored to 'MIN_RESOLVED_TTL'.
+fn ttl_from_valid_until(valid_until: Instant) -> u32 {
+    let now = Instant::now();
+
+    if valid_until > now {
+        cmp::max((valid_until - now).as_secs() as u32, MIN_RESOLVED_TTL)
+    } else {
+        MIN_RESOLVED_TTL
+    }
+}
+
+impl DNSForwardBuilder {
+    fn new() -> DNSForward {
+        // Notice: the forwarder is entirely optional; if no '[dns.forwarders]' section is \
+        //   configured (or forwarding is explicitly disabled), constellation stays purely \
+        //   authoritative and answers any non-authoritative query with a 'SERVFAIL'.
+        let resolver = match APP_CONF.dns.forwarders {
+            Some(ref forwarders_config) if forwarders_config.enabled == true => {
+                info!("dns forwarder is enabled, building upstream resolver");
+
+                Some(ResolverBuilder::from_settings(&ResolverSettings {
+                    nameservers: forwarders_config.nameservers.clone(),
+                    protocol: forwarders_config.protocol.clone(),
+                    timeout: forwarders_config.timeout,
+                    attempts: forwarders_config.attempts,
+                    resolv_file: None,
+                    tls_dns_name: forwarders_config.tls_dns_name.clone(),
+                }))
+            }
+            _ => {
+                info!("dns forwarder is disabled, operating in pure authoritative mode");
+
+                None
+            }
+        };
+
+        DNSForward {
+            registry: RecordRegistry::new(),
+            resolver: resolver,
+        }
+    }
+}
+
+impl DNSForwardBootstrapBuilder {
+    pub fn new() -> DNSForwardBootstrap {
+        DNSForwardBootstrap {}
+    }
+}
+
+impl DNSForwardMaintainBuilder {
+    pub fn new() -> DNSForwardMaintain {
+        // Ensure static is valid and has been built
+        let _ = DNS_FORWARD.deref();
+
+        DNSForwardMaintain {}
+    }
+}
+
+impl DNSForward {
+    pub fn is_enabled(&self) -> bool {
+        self.resolver.is_some()
+    }
+
+    // Scope forwarding to an explicit per-zone allowlist, if configured. With no allowlist set, \
+    //   any non-authoritative query is eligible for forwarding.
+    //
+    // Notice: matching requires an exact zone match or a dot-delimited suffix match, so an \
+    //   allowlist entry of 'example.com' does not also let 'evilexample.com' through.
+    pub fn is_allowed(&self, name: &RecordValue) -> bool {
+        match APP_CONF.dns.forwarders {
+            Some(ref forwarders_config) => {
+                match forwarders_config.allowlist {
+                    Some(ref allowlist) => {
+                        allowlist.iter().any(|allowed_zone| {
+                            name.deref() == allowed_zone.as_str()
+                                || name.ends_with(&format!(".{}", allowed_zone))
+                        })
+                    }
+                    None => true,
+                }
+            }
+            None => false,
+        }
+    }
+
+    pub fn pass(&self, record_type: RecordType, record_value: RecordValue, record_ttl: u32) -> Result<(RecordValues, u32), ()> {
+        if self.resolver.is_none() {
+            return Err(());
+        }
+
+        debug!("forward registry pass on name: {:?} and type: {:?}", record_value, record_type);
+
+        // Acquire registry key
+        let registry_key = (record_value, record_type);
+
+        if let Some(value) = self.registry.lookup(&registry_key) {
+            debug!("forwarding from local registry on name: {:?} and type: {:?}", registry_key.0, registry_key.1);
+
+            Ok(value)
+        } else {
+            info!("forwarding from upstream on name: {:?} and type: {:?}", registry_key.0, registry_key.1);
+
+            self.registry.queue(&registry_key, record_ttl);
+
+            // Notice: this will propagate a 'SERVFAIL', which ensures resolvers do not cache the \
+            //   empty response; the answer will be available on the next query once resolved.
+            Err(())
+        }
+    }
+
+    fn forward(&self, registry_key: &RegistryKey, ttl: u32, accessed_at: Option<SystemTime>) {
+        let resolver = match self.resolver {
+            Some(ref resolver) => resolver,
+            None => return,
+        };
+
+        // Convert each value type into its string representation (same wire formats as the \
+        //   flatten engine, so downstream record rendering stays identical), alongside the TTL \
+        //   actually advertised by the upstream answer (falling back to the seed 'ttl' for the \
+        //   PTR and CNAME types, which perform no lookup and store no values).
+        let resolved: Result<(Vec<String>, u32), ResolveError> = match registry_key.1 {
+            RecordType::A => {
+                resolver.ipv4_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| value.to_string()).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::AAAA => {
+                resolver.ipv6_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| value.to_string()).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::MX => {
+                resolver.mx_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
+                        format!("{} {}", value.preference(), value.exchange())
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::TXT => {
+                resolver.txt_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
+                        concat_txt_segments(value.txt_data().iter().map(|segment| segment.as_ref()))
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::SRV => {
+                resolver.srv_lookup(&registry_key.0).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().map(|value| {
+                        format!("{} {} {} {}", value.priority(), value.weight(), value.port(), value.target())
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::NS => {
+                resolver.lookup(&*registry_key.0, NativeRecordType::NS).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().filter_map(|value| {
+                        match value {
+                            RData::NS(name) => Some(name.to_string()),
+                            _ => None,
+                        }
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::CAA => {
+                resolver.lookup(&*registry_key.0, NativeRecordType::CAA).map(|values| {
+                    let resolved_ttl = ttl_from_valid_until(values.valid_until());
+                    let data = values.iter().filter_map(|value| {
+                        match value {
+                            RData::CAA(caa) => {
+                                let flags = if caa.issuer_critical() { 128 } else { 0 };
+
+                                Some(format!("{} {} \"{}\"", flags, caa.tag(), format_caa_value(caa.value())))
+                            },
+                            _ => None,
+                        }
+                    }).collect();
+
+                    (data, resolved_ttl)
+                })
+            },
+            RecordType::PTR | RecordType::CNAME => Ok((Vec::new(), ttl)),
+        };
+
+        if let Ok((values, resolved_ttl)) = resolved {
+            self.registry.store(
+                registry_key,
+                RecordValues::from_list(values.into_iter().map(RecordValue::from_string).collect()),
+                resolved_ttl,
+                accessed_at,
+            );
+        } else {
+            warn!("dns forwarding in error on name: {:?} and type: {:?}", registry_key.0, registry_key.1);
+        }
+    }
+}
+
+impl DNSForwardBootstrap {
+    pub fn run(&self) {
+        info!("dns forwarder bootstrap is now active");
+
+        loop {
+            thread::sleep(BOOTSTRAP_TICK_INTERVAL);
+
+            Self::tick();
+        }
+    }
+
+    fn tick() {
+        let bootstrap_register = DNS_FORWARD.registry.drain_bootstrap();
+
+        if bootstrap_register.is_empty() == false {
+            for (bootstrap_key, bootstrap_ttl) in bootstrap_register.iter() {
+                DNS_FORWARD.forward(bootstrap_key, *bootstrap_ttl, None);
+                DNS_FORWARD.registry.remove_bootstrap(bootstrap_key);
+            }
+
+            debug!(
+                "bootstrapped dns forwarded records (count: {})",
+                bootstrap_register.len()
+            );
+        }
+    }
+}
+
+impl DNSForwardMaintain {
+    pub fn run(&self) {
+        info!("dns forwarder maintain is now active");
+
+        loop {
+            thread::sleep(MAINTAIN_PERFORM_INTERVAL);
+
+            debug!("running a dns forwarder maintain tick...");
+
+            let flush_start = Instant::now();
+
+            Self::expire();
+            Self::refresh();
+
+            let flush_took = flush_start.elapsed();
+
+            info!(
+                "ran dns forwarder maintain tick (took {}s + {}ms)",
+                flush_took.as_secs(),
+                flush_took.subsec_millis()
+            );
+        }
+    }
+
+    fn expire() {
+        debug!("flushing expired dns forwarded records");
+
+        let expired_count = DNS_FORWARD.registry.expire(MAINTAIN_EXPIRE_TTL_RATIO);
+
+        info!("flushed expired dns forwarded records (count: {})", expired_count);
+    }
+
+    fn refresh() {
+        debug!("refreshing dns forwarded records");
+
+        let refresh_register = DNS_FORWARD.registry.refresh_candidates();
+
+        if refresh_register.is_empty() == false {
+            for (registry_key, registry_ttl, registry_accessed_at) in &refresh_register {
+                DNS_FORWARD.forward(&registry_key, *registry_ttl, Some(*registry_accessed_at));
+            }
+        }
+
+        debug!(
+            "refreshed dns forwarded records (count: {})",
+            refresh_register.len()
+        );
+    }
+}
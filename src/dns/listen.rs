@@ -0,0 +1,191 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::str::FromStr;
+use std::thread;
+
+use trust_dns_server::ServerFuture;
+use trust_dns_server::authority::{MessageResponseBuilder};
+use trust_dns_server::proto::op::{Header, OpCode, ResponseCode};
+use trust_dns_server::proto::rr::{Name, RData, Record, RecordType as NativeRecordType};
+use trust_dns_server::server::{Request, RequestHandler, ResponseHandler};
+
+use super::flatten::{DNSFlattenBootstrapBuilder, DNSFlattenMaintainBuilder};
+use super::forward::{DNSForwardBootstrapBuilder, DNSForwardMaintainBuilder, DNS_FORWARD};
+use super::record::{RecordType, RecordValue, RecordValues};
+use APP_CONF;
+use APP_STORE;
+
+pub struct DNSListenBuilder;
+pub struct DNSListen;
+
+struct DNSRequestHandler;
+
+impl DNSListenBuilder {
+    pub fn new() -> DNSListen {
+        DNSListen {}
+    }
+}
+
+impl DNSListen {
+    pub fn run(&self) {
+        // Spawn the background workers that keep the flatten and (optional) forward registries \
+        //   warm; the query path below answers from the authoritative store or the forward \
+        //   registry only, it never resolves upstream inline (a cold forward lookup is queued and \
+        //   answered on a later retry).
+        //
+        // Notice: the flatten workers are kept running even though the query path below never \
+        //   queues a flatten pass itself -- they exist for configured ALIAS-style zone targets \
+        //   (not yet implemented as a record type), not for raw client query names.
+        Self::spawn_background("constellation-dns-flatten-bootstrap", || {
+            DNSFlattenBootstrapBuilder::new().run()
+        });
+        Self::spawn_background("constellation-dns-flatten-maintain", || {
+            DNSFlattenMaintainBuilder::new().run()
+        });
+
+        if DNS_FORWARD.is_enabled() == true {
+            Self::spawn_background("constellation-dns-forward-bootstrap", || {
+                DNSForwardBootstrapBuilder::new().run()
+            });
+            Self::spawn_background("constellation-dns-forward-maintain", || {
+                DNSForwardMaintainBuilder::new().run()
+            });
+        }
+
+        let socket_addr = format!("{}:{}", APP_CONF.dns.inet, APP_CONF.dns.port);
+
+        info!("dns server is listening on: {}", socket_addr);
+
+        let udp_socket = UdpSocket::bind(&socket_addr).expect("could not bind dns udp socket");
+
+        let mut server = ServerFuture::new(DNSRequestHandler {});
+
+        server.register_socket(udp_socket);
+
+        if let Err(err) = server.listen() {
+            error!("dns server crashed: {}", err);
+        }
+    }
+
+    fn spawn_background<F: Fn() + Send + 'static>(name: &'static str, handler: F) {
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(handler)
+            .expect("could not spawn dns background worker");
+    }
+}
+
+// Seed TTL used only to queue a cold (not-yet-resolved) forward lookup; once resolved, the \
+//   registry entry's TTL is replaced by the upstream answer's own TTL.
+const FORWARD_SEED_TTL: u32 = 300;
+
+impl DNSRequestHandler {
+    // Resolution order: our own authoritative store first (by its own record TTL), then, for \
+    //   non-authoritative misses, the forwarder alone (behind its enabled/allowlist gate).
+    //
+    // Notice: flatten is deliberately NOT consulted here. It only ever resolves configured \
+    //   ALIAS-style zone targets (not yet implemented as a record type) -- never a client's raw, \
+    //   unmatched query name. Running it against the raw query name would make the server act \
+    //   as an open resolver for arbitrary domains regardless of 'dns.forwarders.enabled' or its \
+    //   allowlist, defeating the whole point of those settings.
+    fn lookup(name: &RecordValue, record_type: RecordType) -> Result<(RecordValues, u32), ()> {
+        if let Ok(zone_result) = APP_STORE.zone_lookup(name, record_type) {
+            return Ok(zone_result);
+        }
+
+        if DNS_FORWARD.is_enabled() == true && DNS_FORWARD.is_allowed(name) == true {
+            return DNS_FORWARD.pass(record_type, name.to_owned(), FORWARD_SEED_TTL);
+        }
+
+        Err(())
+    }
+
+    fn native_to_record_type(record_type: NativeRecordType) -> Option<RecordType> {
+        match record_type {
+            NativeRecordType::A => Some(RecordType::A),
+            NativeRecordType::AAAA => Some(RecordType::AAAA),
+            NativeRecordType::MX => Some(RecordType::MX),
+            NativeRecordType::TXT => Some(RecordType::TXT),
+            NativeRecordType::PTR => Some(RecordType::PTR),
+            NativeRecordType::CNAME => Some(RecordType::CNAME),
+            NativeRecordType::SRV => Some(RecordType::SRV),
+            NativeRecordType::NS => Some(RecordType::NS),
+            NativeRecordType::CAA => Some(RecordType::CAA),
+            _ => None,
+        }
+    }
+
+    fn value_to_rdata(record_type: RecordType, value: &RecordValue) -> Option<RData> {
+        match record_type {
+            RecordType::A => Ipv4Addr::from_str(value).ok().map(RData::A),
+            RecordType::AAAA => Ipv6Addr::from_str(value).ok().map(RData::AAAA),
+            RecordType::CNAME => Name::from_str(value).ok().map(RData::CNAME),
+            RecordType::NS => Name::from_str(value).ok().map(RData::NS),
+            RecordType::PTR => Name::from_str(value).ok().map(RData::PTR),
+            _ => None,
+        }
+    }
+}
+
+impl RequestHandler for DNSRequestHandler {
+    fn handle_request<R: ResponseHandler>(&self, request: &Request, mut response_handle: R) -> io::Result<()> {
+        let message = &request.message;
+
+        let mut header = Header::response_from_request(message.header());
+
+        let query = match message.queries().first() {
+            Some(query) => query,
+            None => {
+                header.set_response_code(ResponseCode::FormErr);
+
+                return response_handle.send_response(MessageResponseBuilder::new(None).build_no_records(header));
+            }
+        };
+
+        let native_record_type = query.query_type();
+        let query_name = query.name();
+
+        let answer_records = match Self::native_to_record_type(native_record_type) {
+            Some(record_type) => {
+                // Notice: zone names are stored dot-free, so the FQDN trailing dot is stripped.
+                let name_value = RecordValue::from_string(query_name.to_string().trim_end_matches('.').to_string());
+
+                match Self::lookup(&name_value, record_type) {
+                    Ok((values, ttl)) => {
+                        values
+                            .as_list()
+                            .iter()
+                            .filter_map(|value| {
+                                Self::value_to_rdata(record_type, value).map(|rdata| {
+                                    Record::from_rdata(query_name.clone(), ttl, rdata)
+                                })
+                            })
+                            .collect::<Vec<Record>>()
+                    }
+                    Err(_) => Vec::new(),
+                }
+            }
+            None => Vec::new(),
+        };
+
+        if answer_records.is_empty() == true {
+            header.set_response_code(ResponseCode::ServFail);
+        } else {
+            header.set_response_code(ResponseCode::NoError);
+        }
+
+        header.set_op_code(OpCode::Query);
+        header.set_message_type(message.header().message_type());
+
+        let response = MessageResponseBuilder::new(Some(message.raw_queries()))
+            .build(header, answer_records.iter(), &[], &[], &[]);
+
+        response_handle.send_response(response)
+    }
+}
@@ -0,0 +1,61 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+lazy_static! {
+    pub static ref DNS_FLATTEN_METRICS: DNSFlattenMetrics = DNSFlattenMetrics::default();
+}
+
+#[derive(Default)]
+pub struct DNSFlattenMetrics {
+    entries_tracked: AtomicUsize,
+    refreshes_run: AtomicUsize,
+    changes_detected: AtomicUsize,
+    failures: AtomicUsize,
+    negative_cached: AtomicUsize,
+}
+
+#[derive(Serialize)]
+pub struct DNSFlattenMetricsSnapshot {
+    pub entries_tracked: usize,
+    pub refreshes_run: usize,
+    pub changes_detected: usize,
+    pub failures: usize,
+    pub negative_cached: usize,
+}
+
+impl DNSFlattenMetrics {
+    pub fn track_entry(&self) {
+        self.entries_tracked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn track_refresh(&self) {
+        self.refreshes_run.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn track_change(&self) {
+        self.changes_detected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn track_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn track_negative_cached(&self) {
+        self.negative_cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DNSFlattenMetricsSnapshot {
+        DNSFlattenMetricsSnapshot {
+            entries_tracked: self.entries_tracked.load(Ordering::Relaxed),
+            refreshes_run: self.refreshes_run.load(Ordering::Relaxed),
+            changes_detected: self.changes_detected.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            negative_cached: self.negative_cached.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+pub mod flatten;
+pub mod forward;
+pub mod listen;
+pub mod metrics;
+pub mod record;
+pub mod registry;
+pub mod resolver;
+pub mod zone;
@@ -0,0 +1,112 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::ops::Deref;
+
+use trust_dns_resolver::proto::rr::rdata::caa::Value as CAAValue;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RecordValue(String);
+
+impl RecordValue {
+    pub fn from_string(value: String) -> RecordValue {
+        RecordValue(value)
+    }
+}
+
+impl Deref for RecordValue {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordValues(Vec<RecordValue>);
+
+impl RecordValues {
+    pub fn new() -> RecordValues {
+        RecordValues(Vec::new())
+    }
+
+    pub fn from_list(values: Vec<RecordValue>) -> RecordValues {
+        RecordValues(values)
+    }
+
+    pub fn as_list(&self) -> &[RecordValue] {
+        &self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    AAAA,
+    MX,
+    TXT,
+    PTR,
+    CNAME,
+    SRV,
+    NS,
+    CAA,
+}
+
+// Concatenates raw TXT segment bytes before decoding, so a multi-byte UTF-8 character split \
+//   across a 255-byte segment boundary is decoded correctly, rather than each segment being \
+//   decoded (and potentially mangled) independently.
+pub fn concat_txt_segments<'a, I: IntoIterator<Item = &'a [u8]>>(segments: I) -> String {
+    let raw: Vec<u8> = segments.into_iter().flat_map(|segment| segment.iter().cloned()).collect();
+
+    String::from_utf8_lossy(&raw).into_owned()
+}
+
+// Renders a CAA record's value as its wire-format string, eg. `letsencrypt.org` for an issuer, \
+//   a full `Iodef` URL, or a lossily-decoded UTF-8 dump for an unrecognized property.
+//
+// Notice: trust-dns's CAA 'Value' only derives 'Debug', not 'Display', so it cannot be \
+//   formatted with `{}` directly -- each variant must be rendered explicitly.
+pub fn format_caa_value(value: &CAAValue) -> String {
+    match *value {
+        CAAValue::Issuer(ref name, ref params) => {
+            let issuer = name.as_ref().map(|name| name.to_string()).unwrap_or_else(|| ";".to_string());
+
+            if params.is_empty() {
+                issuer
+            } else {
+                let params_rendered: Vec<String> = params
+                    .iter()
+                    .map(|param| format!("{}={}", param.key(), param.value()))
+                    .collect();
+
+                format!("{}; {}", issuer, params_rendered.join("; "))
+            }
+        }
+        CAAValue::Iodef(ref url) => url.to_string(),
+        CAAValue::Unknown(ref bytes) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_concatenates_segments_before_decoding() {
+        // 'é' (0xC3 0xA9) is split across two segments; decoding each segment independently \
+        //   would turn each half into a replacement character instead of a valid 'é'.
+        let segments: Vec<&[u8]> = vec![&[0xC3], &[0xA9]];
+
+        assert_eq!(concat_txt_segments(segments), "é");
+    }
+
+    #[test]
+    fn it_concatenates_whole_segments_unchanged() {
+        let segments: Vec<&[u8]> = vec![b"hello ", b"world"];
+
+        assert_eq!(concat_txt_segments(segments), "hello world");
+    }
+}
@@ -0,0 +1,239 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use super::record::{RecordType, RecordValue, RecordValues};
+
+pub type RegistryKey = (RecordValue, RecordType);
+
+pub struct RegistryEntry {
+    pub values: RecordValues,
+    pub ttl: u32,
+    pub refreshed_at: SystemTime,
+    pub accessed_at: SystemTime,
+}
+
+impl RegistryEntry {
+    pub fn new(values: RecordValues, ttl: u32, accessed_at: Option<SystemTime>) -> RegistryEntry {
+        let time_now = SystemTime::now();
+
+        RegistryEntry {
+            values: values,
+            ttl: ttl,
+            refreshed_at: time_now,
+            accessed_at: accessed_at.unwrap_or(time_now),
+        }
+    }
+}
+
+pub struct NegativeEntry {
+    pub failed_at: SystemTime,
+    pub backoff: Duration,
+}
+
+// Notice: this is the caching engine shared by the flatten (ALIAS-style resolution of our own \
+//   authoritative records) and forward (upstream resolution of non-authoritative queries) \
+//   subsystems, so both get the same registry/bootstrap/maintain lifecycle for free.
+pub struct RecordRegistry {
+    registry: RwLock<HashMap<RegistryKey, RegistryEntry>>,
+    negative: RwLock<HashMap<RegistryKey, NegativeEntry>>,
+    bootstrap: RwLock<HashMap<RegistryKey, u32>>,
+}
+
+impl RecordRegistry {
+    pub fn new() -> RecordRegistry {
+        RecordRegistry {
+            registry: RwLock::new(HashMap::new()),
+            negative: RwLock::new(HashMap::new()),
+            bootstrap: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Returns the cached value (and its stored TTL) for 'key', bumping its last-access time \
+    //   along the way.
+    pub fn lookup(&self, key: &RegistryKey) -> Option<(RecordValues, u32)> {
+        let mut registry_write = self.registry.write().unwrap();
+
+        if let Some(ref mut entry) = registry_write.get_mut(key) {
+            entry.accessed_at = SystemTime::now();
+
+            Some((entry.values.to_owned(), entry.ttl))
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, key: &RegistryKey) -> bool {
+        self.registry.read().unwrap().contains_key(key)
+    }
+
+    pub fn is_in_backoff(&self, key: &RegistryKey) -> bool {
+        let negative_read = self.negative.read().unwrap();
+
+        if let Some(negative_entry) = negative_read.get(key) {
+            if let Ok(elapsed) = SystemTime::now().duration_since(negative_entry.failed_at) {
+                return elapsed < negative_entry.backoff;
+            }
+        }
+
+        false
+    }
+
+    // Stacks a resolve order to the bootstrap queue (will be picked up by the worker thread).
+    pub fn queue(&self, key: &RegistryKey, ttl: u32) {
+        self.bootstrap.write().unwrap().insert(key.to_owned(), ttl);
+    }
+
+    pub fn drain_bootstrap(&self) -> Vec<(RegistryKey, u32)> {
+        self.bootstrap
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, ttl)| (key.to_owned(), *ttl))
+            .collect()
+    }
+
+    pub fn remove_bootstrap(&self, key: &RegistryKey) {
+        self.bootstrap.write().unwrap().remove(key);
+    }
+
+    // Bumps (or creates) the negative cache entry for 'key', doubling the backoff on each \
+    //   consecutive failure, up to 'ceiling'.
+    pub fn negative_bump(&self, key: &RegistryKey, floor: Duration, ceiling: Duration) -> Duration {
+        let mut negative_write = self.negative.write().unwrap();
+
+        let next_backoff = if let Some(negative_entry) = negative_write.get(key) {
+            cmp::min(negative_entry.backoff * 2, ceiling)
+        } else {
+            floor
+        };
+
+        negative_write.insert(
+            key.to_owned(),
+
+            NegativeEntry {
+                failed_at: SystemTime::now(),
+                backoff: next_backoff,
+            },
+        );
+
+        next_backoff
+    }
+
+    pub fn negative_clear(&self, key: &RegistryKey) {
+        self.negative.write().unwrap().remove(key);
+    }
+
+    // Stores freshly-resolved values, returning the previously-cached values (if any), so \
+    //   callers can detect a change.
+    pub fn store(&self, key: &RegistryKey, values: RecordValues, ttl: u32, accessed_at: Option<SystemTime>) -> Option<RecordValues> {
+        let mut registry_write = self.registry.write().unwrap();
+
+        let previous = registry_write.get(key).map(|entry| entry.values.to_owned());
+
+        registry_write.insert(key.to_owned(), RegistryEntry::new(values, ttl, accessed_at));
+
+        previous
+    }
+
+    pub fn expire(&self, ttl_ratio: u32) -> usize {
+        let mut expire_register: Vec<RegistryKey> = Vec::new();
+
+        {
+            let registry_read = self.registry.read().unwrap();
+            let now_time = SystemTime::now();
+
+            for (registry_key, registry_entry) in registry_read.iter() {
+                let registry_elapsed = now_time
+                    .duration_since(registry_entry.accessed_at)
+                    .unwrap()
+                    .as_secs();
+
+                if registry_elapsed >= (registry_entry.ttl * ttl_ratio) as u64 {
+                    expire_register.push(registry_key.to_owned());
+                }
+            }
+        }
+
+        if expire_register.is_empty() == false {
+            let mut registry_write = self.registry.write().unwrap();
+
+            for registry_key in &expire_register {
+                registry_write.remove(registry_key);
+            }
+        }
+
+        expire_register.len()
+    }
+
+    pub fn refresh_candidates(&self) -> Vec<(RegistryKey, u32, SystemTime)> {
+        let registry_read = self.registry.read().unwrap();
+        let now_time = SystemTime::now();
+
+        let mut refresh_register = Vec::new();
+
+        for (registry_key, registry_entry) in registry_read.iter() {
+            let registry_elapsed = now_time
+                .duration_since(registry_entry.refreshed_at)
+                .unwrap()
+                .as_secs();
+
+            if registry_elapsed >= registry_entry.ttl as u64 {
+                refresh_register.push((registry_key.to_owned(), registry_entry.ttl, registry_entry.accessed_at));
+            }
+        }
+
+        refresh_register
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: &str) -> RegistryKey {
+        (RecordValue::from_string(value.to_string()), RecordType::A)
+    }
+
+    #[test]
+    fn it_doubles_backoff_up_to_ceiling() {
+        let registry = RecordRegistry::new();
+        let registry_key = key("flaky.example.com");
+
+        let floor = Duration::from_secs(5);
+        let ceiling = Duration::from_secs(30);
+
+        assert_eq!(registry.negative_bump(&registry_key, floor, ceiling), Duration::from_secs(5));
+        assert_eq!(registry.negative_bump(&registry_key, floor, ceiling), Duration::from_secs(10));
+        assert_eq!(registry.negative_bump(&registry_key, floor, ceiling), Duration::from_secs(20));
+
+        // Notice: doubling past this point would exceed 'ceiling', so it stays capped.
+        assert_eq!(registry.negative_bump(&registry_key, floor, ceiling), Duration::from_secs(30));
+        assert_eq!(registry.negative_bump(&registry_key, floor, ceiling), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn it_returns_previous_value_on_store_for_change_detection() {
+        let registry = RecordRegistry::new();
+        let registry_key = key("flattened.example.com");
+
+        let first_values = RecordValues::from_list(vec![RecordValue::from_string("1.2.3.4".to_string())]);
+        let second_values = RecordValues::from_list(vec![RecordValue::from_string("5.6.7.8".to_string())]);
+
+        // First store has nothing to compare against, so no change can be detected yet.
+        assert_eq!(registry.store(&registry_key, first_values.clone(), 300, None), None);
+
+        // Second store returns the previous values, letting the caller detect the re-point.
+        let previous = registry.store(&registry_key, second_values.clone(), 300, None);
+
+        assert_eq!(previous, Some(first_values));
+        assert_ne!(previous, Some(second_values));
+    }
+}
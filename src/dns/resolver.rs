@@ -0,0 +1,303 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use trust_dns_resolver::Resolver;
+use trust_dns_resolver::config::{
+    NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+};
+
+const RESOLVER_DEFAULT_PORT_UDP_TCP: u16 = 53;
+const RESOLVER_DEFAULT_PORT_TLS: u16 = 853;
+
+#[derive(Clone)]
+pub struct ResolverSettings {
+    pub nameservers: Vec<String>,
+    pub protocol: String,
+    pub timeout: u64,
+    pub attempts: usize,
+    pub resolv_file: Option<String>,
+    pub tls_dns_name: Option<String>,
+}
+
+impl Default for ResolverSettings {
+    fn default() -> ResolverSettings {
+        ResolverSettings {
+            nameservers: Vec::new(),
+            protocol: "udp".to_string(),
+            timeout: 5,
+            attempts: 2,
+            resolv_file: None,
+            tls_dns_name: None,
+        }
+    }
+}
+
+// Notice: 'nameserver' lines resolve upstream addresses, while 'options' lines may tune the \
+//   resolver itself (eg. 'timeout:N', 'attempts:N'), same as a system resolv.conf.
+#[derive(Default)]
+struct ResolvFile {
+    nameservers: Vec<IpAddr>,
+    timeout: Option<u64>,
+    attempts: Option<usize>,
+}
+
+pub struct ResolverBuilder;
+
+impl ResolverBuilder {
+    pub fn from_settings(settings: &ResolverSettings) -> Resolver {
+        let (config, opts) = Self::make_config(settings);
+
+        if let Ok(resolver) = Resolver::new(config, opts) {
+            info!("dns resolver acquired from explicit configuration");
+
+            resolver
+        } else {
+            warn!("dns resolver could not be built from explicit configuration, falling back to system resolver");
+
+            Self::from_system_or_default()
+        }
+    }
+
+    pub fn from_system_or_default() -> Resolver {
+        if let Ok(resolver) = Resolver::from_system_conf() {
+            info!("dns resolver acquired from system");
+
+            resolver
+        } else {
+            warn!("dns resolver could not be acquired from system, using default resolver");
+
+            Resolver::new(ResolverConfig::default(), ResolverOpts::default()).unwrap()
+        }
+    }
+
+    fn make_config(settings: &ResolverSettings) -> (ResolverConfig, ResolverOpts) {
+        let protocol = Self::parse_protocol(&settings.protocol);
+
+        // Explicit nameservers take precedence over a pointed resolv.conf file
+        let (nameservers, resolv_timeout, resolv_attempts) = if settings.nameservers.is_empty() == false {
+            let nameservers = settings
+                .nameservers
+                .iter()
+                .filter_map(|nameserver| match nameserver.parse::<IpAddr>() {
+                    Ok(ip_addr) => Some(ip_addr),
+                    Err(_) => {
+                        warn!("could not parse configured nameserver address: {}", nameserver);
+
+                        None
+                    }
+                })
+                .collect();
+
+            (nameservers, None, None)
+        } else if let Some(ref resolv_file) = settings.resolv_file {
+            let resolv_file = Self::parse_resolv_file(resolv_file);
+
+            (resolv_file.nameservers, resolv_file.timeout, resolv_file.attempts)
+        } else {
+            (Vec::new(), None, None)
+        };
+
+        // Notice: DNS-over-TLS requires a server name to validate the upstream's certificate \
+        //   against; without one, trust-dns has nothing to check the presented certificate's \
+        //   subject name against, so a DoT nameserver must always be paired with 'tls_dns_name'.
+        if protocol == Protocol::Tls && settings.tls_dns_name.is_none() {
+            warn!("dns-over-tls resolver configured without a 'tls_dns_name', falling back to default resolver configuration");
+
+            return (ResolverConfig::default(), ResolverOpts::default());
+        }
+
+        let mut config = ResolverConfig::new();
+
+        for nameserver in nameservers {
+            let socket_addr = SocketAddr::new(
+                nameserver,
+                match protocol {
+                    Protocol::Tcp | Protocol::Udp => RESOLVER_DEFAULT_PORT_UDP_TCP,
+                    _ => RESOLVER_DEFAULT_PORT_TLS,
+                },
+            );
+
+            config.add_name_server(NameServerConfig {
+                socket_addr: socket_addr,
+                protocol: protocol,
+                tls_dns_name: settings.tls_dns_name.clone(),
+            });
+        }
+
+        // No explicit nameserver could be resolved, fall back to well-known defaults
+        if config.name_servers().is_empty() == true {
+            config = ResolverConfig::default();
+        }
+
+        let mut opts = ResolverOpts::default();
+
+        // 'options' parsed from a resolv file override the base settings, same as a system \
+        //   resolv.conf would tune the stub resolver it is attached to.
+        opts.timeout = Duration::from_secs(resolv_timeout.unwrap_or(settings.timeout));
+        opts.attempts = resolv_attempts.unwrap_or(settings.attempts);
+
+        (config, opts)
+    }
+
+    fn parse_protocol(protocol: &str) -> Protocol {
+        match protocol.to_lowercase().as_str() {
+            "tcp" => Protocol::Tcp,
+            "tls" => Protocol::Tls,
+            _ => Protocol::Udp,
+        }
+    }
+
+    fn parse_resolv_file(path: &str) -> ResolvFile {
+        let mut resolv_file = ResolvFile::default();
+
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    Self::parse_resolv_line(line, &mut resolv_file);
+                }
+            }
+            Err(err) => {
+                warn!("could not read pointed resolv file: {} ({})", path, err);
+            }
+        }
+
+        resolv_file
+    }
+
+    fn parse_resolv_line(line: &str, resolv_file: &mut ResolvFile) {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return;
+        }
+
+        let mut parts = line.split_whitespace();
+
+        match parts.next() {
+            Some("nameserver") => {
+                if let Some(address) = parts.next() {
+                    match address.parse::<IpAddr>() {
+                        Ok(ip_addr) => resolv_file.nameservers.push(ip_addr),
+                        Err(_) => warn!("could not parse nameserver address in resolv file: {}", address),
+                    }
+                }
+            }
+            Some("options") => {
+                for option in parts {
+                    let mut option_parts = option.splitn(2, ':');
+
+                    match (option_parts.next(), option_parts.next()) {
+                        (Some("timeout"), Some(value)) => match value.parse::<u64>() {
+                            Ok(timeout) => resolv_file.timeout = Some(timeout),
+                            Err(_) => warn!("could not parse 'timeout' option in resolv file: {}", value),
+                        },
+                        (Some("attempts"), Some(value)) => match value.parse::<usize>() {
+                            Ok(attempts) => resolv_file.attempts = Some(attempts),
+                            Err(_) => warn!("could not parse 'attempts' option in resolv file: {}", value),
+                        },
+                        _ => {
+                            // Notice: other resolv.conf options (eg. 'ndots', 'rotate') do not \
+                            //   map to anything the underlying resolver exposes, so they are \
+                            //   intentionally ignored rather than rejected.
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_nameserver_lines() {
+        let mut resolv_file = ResolvFile::default();
+
+        ResolverBuilder::parse_resolv_line("nameserver 1.1.1.1", &mut resolv_file);
+        ResolverBuilder::parse_resolv_line("nameserver 2606:4700:4700::1111", &mut resolv_file);
+        ResolverBuilder::parse_resolv_line("; a comment", &mut resolv_file);
+        ResolverBuilder::parse_resolv_line("nameserver not-an-ip", &mut resolv_file);
+
+        assert_eq!(resolv_file.nameservers.len(), 2);
+    }
+
+    #[test]
+    fn it_parses_options_line() {
+        let mut resolv_file = ResolvFile::default();
+
+        ResolverBuilder::parse_resolv_line("options timeout:3 attempts:4 ndots:2", &mut resolv_file);
+
+        assert_eq!(resolv_file.timeout, Some(3));
+        assert_eq!(resolv_file.attempts, Some(4));
+    }
+
+    #[test]
+    fn it_falls_back_to_default_config_when_no_nameserver_resolves() {
+        let settings = ResolverSettings {
+            nameservers: vec!["not-an-ip".to_string()],
+            ..ResolverSettings::default()
+        };
+
+        let (config, _) = ResolverBuilder::make_config(&settings);
+
+        assert_eq!(config.name_servers().is_empty(), false);
+    }
+
+    #[test]
+    fn it_applies_explicit_timeout_and_attempts() {
+        let settings = ResolverSettings {
+            timeout: 7,
+            attempts: 9,
+            ..ResolverSettings::default()
+        };
+
+        let (_, opts) = ResolverBuilder::make_config(&settings);
+
+        assert_eq!(opts.timeout, Duration::from_secs(7));
+        assert_eq!(opts.attempts, 9);
+    }
+
+    #[test]
+    fn it_refuses_tls_without_a_tls_dns_name() {
+        let settings = ResolverSettings {
+            nameservers: vec!["1.1.1.1".to_string()],
+            protocol: "tls".to_string(),
+            ..ResolverSettings::default()
+        };
+
+        let (config, _) = ResolverBuilder::make_config(&settings);
+        let default_config = ResolverConfig::default();
+
+        // Notice: falls back to the well-known default config, rather than building a DoT \
+        //   nameserver with no certificate name to validate against.
+        assert_eq!(config.name_servers().len(), default_config.name_servers().len());
+    }
+
+    #[test]
+    fn it_builds_a_tls_nameserver_when_a_tls_dns_name_is_set() {
+        let settings = ResolverSettings {
+            nameservers: vec!["1.1.1.1".to_string()],
+            protocol: "tls".to_string(),
+            tls_dns_name: Some("cloudflare-dns.com".to_string()),
+            ..ResolverSettings::default()
+        };
+
+        let (config, _) = ResolverBuilder::make_config(&settings);
+
+        assert_eq!(config.name_servers().len(), 1);
+        assert_eq!(
+            config.name_servers()[0].tls_dns_name,
+            Some("cloudflare-dns.com".to_string())
+        );
+    }
+}
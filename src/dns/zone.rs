@@ -0,0 +1,373 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::fmt;
+use std::fs;
+
+use APP_STORE;
+
+const DEFAULT_TTL: u32 = 3600;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ZoneRecordType {
+    A,
+    AAAA,
+    MX,
+    TXT,
+    CNAME,
+    PTR,
+    NS,
+}
+
+impl ZoneRecordType {
+    fn from_str(value: &str) -> Option<ZoneRecordType> {
+        match value.to_uppercase().as_str() {
+            "A" => Some(ZoneRecordType::A),
+            "AAAA" => Some(ZoneRecordType::AAAA),
+            "MX" => Some(ZoneRecordType::MX),
+            "TXT" => Some(ZoneRecordType::TXT),
+            "CNAME" => Some(ZoneRecordType::CNAME),
+            "PTR" => Some(ZoneRecordType::PTR),
+            "NS" => Some(ZoneRecordType::NS),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ZoneRecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match *self {
+            ZoneRecordType::A => "A",
+            ZoneRecordType::AAAA => "AAAA",
+            ZoneRecordType::MX => "MX",
+            ZoneRecordType::TXT => "TXT",
+            ZoneRecordType::CNAME => "CNAME",
+            ZoneRecordType::PTR => "PTR",
+            ZoneRecordType::NS => "NS",
+        };
+
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZoneSOA {
+    pub m_name: String,
+    pub r_name: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZoneRecord {
+    pub name: String,
+    pub record_type: ZoneRecordType,
+    pub ttl: u32,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Zone {
+    pub origin: String,
+    pub soa: Option<ZoneSOA>,
+    pub records: Vec<ZoneRecord>,
+}
+
+pub struct ZoneFile;
+
+impl ZoneFile {
+    pub fn import_from_path(path: &str) -> Result<Zone, String> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            format!("could not read zone file '{}': {}", path, err)
+        })?;
+
+        Self::parse(&contents)
+    }
+
+    pub fn import_from_contents(contents: &str) -> Result<Zone, String> {
+        Self::parse(contents)
+    }
+
+    pub fn import_into_store(zone: &Zone) -> Result<(), ()> {
+        info!(
+            "importing zone file for origin: {} (records: {})",
+            zone.origin,
+            zone.records.len()
+        );
+
+        APP_STORE.zone_import(zone)
+    }
+
+    pub fn export_from_store(origin: &str) -> Result<Zone, ()> {
+        APP_STORE.zone_export(origin)
+    }
+
+    pub fn dump(zone: &Zone) -> String {
+        let mut output = String::new();
+
+        output.push_str(&format!("$ORIGIN {}\n", zone.origin));
+        output.push_str(&format!("$TTL {}\n", DEFAULT_TTL));
+
+        if let Some(ref soa) = zone.soa {
+            output.push_str(&format!(
+                "@ IN SOA {} {} ( {} {} {} {} {} )\n",
+                soa.m_name, soa.r_name, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum
+            ));
+        }
+
+        for record in &zone.records {
+            output.push_str(&format!(
+                "{} {} IN {} {}\n",
+                record.name, record.ttl, record.record_type, record.value
+            ));
+        }
+
+        output
+    }
+
+    fn parse(contents: &str) -> Result<Zone, String> {
+        let mut origin = String::new();
+        let mut default_ttl = DEFAULT_TTL;
+        let mut soa = None;
+        let mut records = Vec::new();
+
+        // Notice: this is a pragmatic RFC1035 master-file parser, it does not support nested \
+        //   parentheses or multi-line TXT continuations beyond the SOA record.
+        let mut pending_soa: Option<Vec<String>> = None;
+
+        for raw_line in contents.lines() {
+            let line = Self::strip_comment(raw_line).trim().to_string();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(ref mut soa_tokens) = pending_soa {
+                soa_tokens.extend(Self::tokenize(&line));
+
+                if line.contains(')') {
+                    let soa_tokens = pending_soa.take().unwrap();
+
+                    soa = Some(Self::parse_soa(&soa_tokens)?);
+                }
+
+                continue;
+            }
+
+            if line.starts_with("$ORIGIN") {
+                // Notice: stored dot-free, like every other name in a parsed 'Zone' -- the \
+                //   trailing dot is master-file FQDN notation, not part of the origin itself.
+                origin = Self::tokenize(&line)
+                    .get(1)
+                    .map(|token| token.trim_end_matches('.').to_string())
+                    .unwrap_or_default();
+
+                continue;
+            }
+
+            if line.starts_with("$TTL") {
+                default_ttl = Self::tokenize(&line)
+                    .get(1)
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(DEFAULT_TTL);
+
+                continue;
+            }
+
+            let tokens = Self::tokenize(&line);
+
+            if tokens.iter().any(|token| token == "SOA") {
+                if line.contains('(') && line.contains(')') == false {
+                    pending_soa = Some(tokens);
+                } else {
+                    soa = Some(Self::parse_soa(&tokens)?);
+                }
+
+                continue;
+            }
+
+            if let Some(record) = Self::parse_record(&tokens, &origin, default_ttl) {
+                records.push(record);
+            }
+        }
+
+        Ok(Zone {
+            origin: origin,
+            soa: soa,
+            records: records,
+        })
+    }
+
+    fn parse_soa(tokens: &[String]) -> Result<ZoneSOA, String> {
+        // Expected shape (order-preserved, parentheses stripped): \
+        //   <name> IN SOA <m_name> <r_name> ( <serial> <refresh> <retry> <expire> <minimum> )
+        let cleaned: Vec<&String> = tokens
+            .iter()
+            .filter(|token| token.as_str() != "(" && token.as_str() != ")")
+            .collect();
+
+        let soa_index = cleaned
+            .iter()
+            .position(|token| token.as_str() == "SOA")
+            .ok_or_else(|| "malformed SOA record: missing SOA keyword".to_string())?;
+
+        let rest = &cleaned[soa_index + 1..];
+
+        if rest.len() < 7 {
+            return Err("malformed SOA record: not enough fields".to_string());
+        }
+
+        Ok(ZoneSOA {
+            m_name: rest[0].trim_end_matches('.').to_string(),
+            r_name: rest[1].trim_end_matches('.').to_string(),
+            serial: rest[2].parse().map_err(|_| "invalid SOA serial")?,
+            refresh: rest[3].parse().map_err(|_| "invalid SOA refresh")?,
+            retry: rest[4].parse().map_err(|_| "invalid SOA retry")?,
+            expire: rest[5].parse().map_err(|_| "invalid SOA expire")?,
+            minimum: rest[6].parse().map_err(|_| "invalid SOA minimum")?,
+        })
+    }
+
+    fn parse_record(tokens: &[String], origin: &str, default_ttl: u32) -> Option<ZoneRecord> {
+        if tokens.is_empty() {
+            return None;
+        }
+
+        let mut index = 0;
+
+        let name = Self::qualify_name(&tokens[0], origin);
+
+        index += 1;
+
+        // An explicit per-record TTL may follow the name
+        let mut ttl = default_ttl;
+
+        if let Some(maybe_ttl) = tokens.get(index) {
+            if let Ok(parsed_ttl) = maybe_ttl.parse::<u32>() {
+                ttl = parsed_ttl;
+                index += 1;
+            }
+        }
+
+        // Skip the class token (eg. 'IN'), if present
+        if tokens.get(index).map(String::as_str) == Some("IN") {
+            index += 1;
+        }
+
+        let record_type = tokens.get(index).and_then(|token| ZoneRecordType::from_str(token))?;
+
+        index += 1;
+
+        let value = tokens[index..].join(" ");
+        let value = Self::qualify_value(&record_type, &value, origin);
+
+        Some(ZoneRecord {
+            name: name,
+            record_type: record_type,
+            ttl: ttl,
+            value: value,
+        })
+    }
+
+    fn qualify_name(name: &str, origin: &str) -> String {
+        if name == "@" {
+            origin.to_string()
+        } else if name.ends_with('.') {
+            name.trim_end_matches('.').to_string()
+        } else {
+            format!("{}.{}", name, origin)
+        }
+    }
+
+    fn qualify_value(record_type: &ZoneRecordType, value: &str, origin: &str) -> String {
+        match record_type {
+            ZoneRecordType::CNAME | ZoneRecordType::NS | ZoneRecordType::PTR => {
+                Self::qualify_name(value, origin)
+            }
+            _ => value.to_string(),
+        }
+    }
+
+    fn strip_comment(line: &str) -> &str {
+        match line.find(';') {
+            Some(index) => &line[..index],
+            None => line,
+        }
+    }
+
+    fn tokenize(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|token| token.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_a_multi_line_soa_record() {
+        let contents = "\
+$ORIGIN example.com.
+$TTL 3600
+@ IN SOA ns1.example.com. hostmaster.example.com. (
+    2020010100 ; serial
+    7200       ; refresh
+    3600       ; retry
+    1209600    ; expire
+    300        ; minimum
+)
+";
+
+        let zone = ZoneFile::import_from_contents(contents).expect("should parse zone");
+        let soa = zone.soa.expect("should have parsed a SOA record");
+
+        assert_eq!(zone.origin, "example.com");
+        assert_eq!(soa.m_name, "ns1.example.com");
+        assert_eq!(soa.r_name, "hostmaster.example.com");
+        assert_eq!(soa.serial, 2020010100);
+        assert_eq!(soa.refresh, 7200);
+        assert_eq!(soa.retry, 3600);
+        assert_eq!(soa.expire, 1209600);
+        assert_eq!(soa.minimum, 300);
+    }
+
+    #[test]
+    fn it_parses_records_and_qualifies_relative_names() {
+        let contents = "\
+$ORIGIN example.com.
+$TTL 3600
+@ IN SOA ns1.example.com. hostmaster.example.com. ( 1 2 3 4 5 )
+www IN A 1.2.3.4
+mail 300 IN MX 10 mailhost
+";
+
+        let zone = ZoneFile::import_from_contents(contents).expect("should parse zone");
+
+        assert_eq!(zone.records.len(), 2);
+
+        assert_eq!(zone.records[0].name, "www.example.com");
+        assert_eq!(zone.records[0].record_type, ZoneRecordType::A);
+        assert_eq!(zone.records[0].ttl, 3600);
+        assert_eq!(zone.records[0].value, "1.2.3.4");
+
+        assert_eq!(zone.records[1].name, "mail.example.com");
+        assert_eq!(zone.records[1].ttl, 300);
+        assert_eq!(zone.records[1].value, "10 mailhost");
+    }
+
+    #[test]
+    fn it_rejects_a_malformed_soa_record() {
+        let contents = "\
+$ORIGIN example.com.
+@ IN SOA ns1.example.com. hostmaster.example.com. ( 1 2 3 )
+";
+
+        assert!(ZoneFile::import_from_contents(contents).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use rocket;
+
+use super::metrics;
+use super::zone;
+
+pub struct HTTPListenBuilder;
+pub struct HTTPListen;
+
+impl HTTPListenBuilder {
+    pub fn new() -> HTTPListen {
+        HTTPListen {}
+    }
+}
+
+impl HTTPListen {
+    pub fn run(&self) {
+        info!("starting http server");
+
+        rocket::ignite()
+            .mount(
+                "/",
+                routes![zone::push, zone::pull, metrics::get_flatten_metrics],
+            )
+            .launch();
+    }
+}
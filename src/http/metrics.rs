@@ -0,0 +1,14 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use rocket_contrib::Json;
+
+use dns::metrics::{DNS_FLATTEN_METRICS, DNSFlattenMetricsSnapshot};
+
+#[get("/dns/flatten/metrics")]
+pub fn get_flatten_metrics() -> Json<DNSFlattenMetricsSnapshot> {
+    Json(DNS_FLATTEN_METRICS.snapshot())
+}
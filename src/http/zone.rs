@@ -0,0 +1,51 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2020, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::io::Read;
+
+use rocket::Data;
+use rocket::http::Status;
+use rocket_contrib::Json;
+
+use dns::zone::ZoneFile;
+
+#[derive(Serialize)]
+pub struct ZoneDumpResponse {
+    origin: String,
+    contents: String,
+}
+
+#[post("/dns/zone/<origin>", data = "<data>")]
+pub fn push(origin: String, data: Data) -> Status {
+    let mut contents = String::new();
+
+    if data.open().read_to_string(&mut contents).is_err() {
+        return Status::BadRequest;
+    }
+
+    match ZoneFile::import_from_contents(&contents) {
+        Ok(ref zone) if zone.origin == origin => {
+            match ZoneFile::import_into_store(zone) {
+                Ok(_) => Status::Ok,
+                Err(_) => Status::InternalServerError,
+            }
+        }
+        Ok(_) => Status::BadRequest,
+        Err(_) => Status::BadRequest,
+    }
+}
+
+#[get("/dns/zone/<origin>")]
+pub fn pull(origin: String) -> Result<Json<ZoneDumpResponse>, Status> {
+    ZoneFile::export_from_store(&origin)
+        .map(|zone| {
+            Json(ZoneDumpResponse {
+                origin: zone.origin.clone(),
+                contents: ZoneFile::dump(&zone),
+            })
+        })
+        .map_err(|_| Status::NotFound)
+}
@@ -33,6 +33,7 @@ mod dns;
 mod http;
 mod store;
 
+use std::process;
 use std::thread;
 use std::ops::Deref;
 use std::str::FromStr;
@@ -46,10 +47,12 @@ use config::logger::ConfigLogger;
 use config::reader::ConfigReader;
 use store::store::{Store, StoreBuilder};
 use dns::listen::DNSListenBuilder;
+use dns::zone::ZoneFile;
 use http::listen::HTTPListenBuilder;
 
 struct AppArgs {
     config: String,
+    import_zone: Option<String>,
 }
 
 pub static THREAD_NAME_DNS: &'static str = "constellation-dns";
@@ -116,10 +119,19 @@ fn make_app_args() -> AppArgs {
                 .default_value("./config.cfg")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("import-zone")
+                .long("import-zone")
+                .help("Path to an RFC1035 zone file to import into the store, then exit")
+                .takes_value(true),
+        )
         .get_matches();
 
     // Generate owned app arguments
-    AppArgs { config: String::from(matches.value_of("config").expect("invalid config value")) }
+    AppArgs {
+        config: String::from(matches.value_of("config").expect("invalid config value")),
+        import_zone: matches.value_of("import-zone").map(String::from),
+    }
 }
 
 fn ensure_states() {
@@ -139,6 +151,24 @@ fn main() {
     // Ensure all states are bound
     ensure_states();
 
+    // Import a zone file, then exit? (one-shot maintenance mode)
+    if let Some(ref import_zone) = APP_ARGS.import_zone {
+        return match ZoneFile::import_from_path(import_zone)
+            .map_err(|err| log::error!("failed parsing zone file: {}", err))
+            .and_then(|zone| {
+                ZoneFile::import_into_store(&zone).map_err(|_| {
+                    log::error!("failed importing zone file into store");
+                })
+            }) {
+            Ok(_) => log::info!("zone file imported: {}", import_zone),
+            Err(_) => {
+                log::error!("could not import zone file: {}", import_zone);
+
+                process::exit(1);
+            }
+        };
+    }
+
     // Spawn HTTP server (background thread)
     thread::spawn(spawn_http);
 
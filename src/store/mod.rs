@@ -0,0 +1,7 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+pub mod store;
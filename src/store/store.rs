@@ -0,0 +1,137 @@
+// Constellation
+//
+// Pluggable authoritative DNS server
+// Copyright: 2018, Valerian Saliou <valerian@valeriansaliou.name>
+// License: Mozilla Public License v2.0 (MPL v2.0)
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::RwLock;
+
+use r2d2::Pool;
+use r2d2_redis::RedisConnectionManager;
+use r2d2_redis::redis::Commands;
+use serde_json;
+
+use dns::record::{RecordType, RecordValue, RecordValues};
+use dns::zone::{Zone, ZoneRecord, ZoneRecordType};
+use APP_CONF;
+
+const STORE_KEY_PREFIX: &'static str = "constellation:zone:";
+
+pub struct StoreBuilder;
+
+// Notice: imported zones are persisted to Redis (source of truth, survives a restart) and kept \
+//   in an in-memory cache (same RwLock<HashMap> idiom used by the flatten/forward registries) \
+//   so the query path can answer authoritative records without a round-trip to the store.
+pub struct Store {
+    pool: Pool<RedisConnectionManager>,
+    cache: RwLock<HashMap<String, Zone>>,
+}
+
+impl StoreBuilder {
+    pub fn new() -> Store {
+        let manager = RedisConnectionManager::new(APP_CONF.store.redis_url.as_str())
+            .expect("invalid store redis url");
+
+        let pool = Pool::builder().build(manager).expect(
+            "could not build store redis pool",
+        );
+
+        Store {
+            pool: pool,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl Store {
+    pub fn zone_import(&self, zone: &Zone) -> Result<(), ()> {
+        let payload = serde_json::to_string(zone).map_err(|_| ())?;
+
+        let mut connection = self.pool.get().map_err(|_| ())?;
+
+        connection
+            .set::<_, _, ()>(Self::key(&zone.origin), payload)
+            .map_err(|_| ())?;
+
+        self.cache.write().unwrap().insert(
+            zone.origin.clone(),
+            zone.to_owned(),
+        );
+
+        Ok(())
+    }
+
+    pub fn zone_export(&self, origin: &str) -> Result<Zone, ()> {
+        if let Some(zone) = self.cache.read().unwrap().get(origin) {
+            return Ok(zone.to_owned());
+        }
+
+        let mut connection = self.pool.get().map_err(|_| ())?;
+
+        let payload: String = connection.get(Self::key(origin)).map_err(|_| ())?;
+        let zone: Zone = serde_json::from_str(&payload).map_err(|_| ())?;
+
+        self.cache.write().unwrap().insert(
+            origin.to_string(),
+            zone.clone(),
+        );
+
+        Ok(zone)
+    }
+
+    // Answers an authoritative query straight from the in-memory zone cache; used by the DNS \
+    //   query path ahead of the forward fallback. Returns the matched records' own TTL \
+    //   (the lowest one, if they differ) alongside their values.
+    pub fn zone_lookup(&self, name: &RecordValue, record_type: RecordType) -> Result<(RecordValues, u32), ()> {
+        let cache_read = self.cache.read().unwrap();
+
+        for zone in cache_read.values() {
+            if Self::is_in_zone(name, &zone.origin) == false {
+                continue;
+            }
+
+            let matched: Vec<&ZoneRecord> = zone
+                .records
+                .iter()
+                .filter(|record| {
+                    record.name.as_str() == name.deref() && Self::record_type_matches(&record.record_type, record_type)
+                })
+                .collect();
+
+            if matched.is_empty() == false {
+                let ttl = matched.iter().map(|record| record.ttl).min().unwrap_or(0);
+                let values = matched
+                    .into_iter()
+                    .map(|record| RecordValue::from_string(record.value.clone()))
+                    .collect();
+
+                return Ok((RecordValues::from_list(values), ttl));
+            }
+        }
+
+        Err(())
+    }
+
+    fn is_in_zone(name: &RecordValue, origin: &str) -> bool {
+        name.deref() == origin || name.ends_with(&format!(".{}", origin))
+    }
+
+    fn record_type_matches(zone_record_type: &ZoneRecordType, record_type: RecordType) -> bool {
+        match (zone_record_type, record_type) {
+            (ZoneRecordType::A, RecordType::A) => true,
+            (ZoneRecordType::AAAA, RecordType::AAAA) => true,
+            (ZoneRecordType::MX, RecordType::MX) => true,
+            (ZoneRecordType::TXT, RecordType::TXT) => true,
+            (ZoneRecordType::CNAME, RecordType::CNAME) => true,
+            (ZoneRecordType::PTR, RecordType::PTR) => true,
+            (ZoneRecordType::NS, RecordType::NS) => true,
+            _ => false,
+        }
+    }
+
+    fn key(origin: &str) -> String {
+        format!("{}{}", STORE_KEY_PREFIX, origin)
+    }
+}